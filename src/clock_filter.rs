@@ -0,0 +1,127 @@
+//! NTP clock filter: selects the lowest-delay sample from a short history of
+//! offset/delay measurements, per the noise-rejection stage in RFC 5905 §10.
+
+use crate::NtpDuration;
+
+const FILTER_SIZE: usize = 8;
+
+#[derive(Clone, Copy)]
+struct Sample {
+    offset: NtpDuration,
+    delay: NtpDuration,
+    dispersion: i64,
+    epoch: u32,
+}
+
+/// keeps a shift register of the last [`FILTER_SIZE`] `(offset, delay,
+/// dispersion, epoch)` samples and picks the lowest-delay one as the current
+/// best estimate, ageing stored dispersions as new samples arrive
+pub struct ClockFilter {
+    register: [Option<Sample>; FILTER_SIZE],
+    next: usize,
+    /// dispersion added to every stored sample on each new poll, so stale
+    /// samples lose weight over time
+    dispersion_increment: i64,
+}
+
+impl ClockFilter {
+    pub fn new(dispersion_increment: i64) -> Self {
+        Self {
+            register: [None; FILTER_SIZE],
+            next: 0,
+            dispersion_increment,
+        }
+    }
+
+    /// shift a new `(offset, delay, dispersion)` sample into the register,
+    /// ageing every previously stored sample first
+    pub fn add_sample(&mut self, offset: NtpDuration, delay: NtpDuration, dispersion: i64, epoch: u32) {
+        for slot in self.register.iter_mut().flatten() {
+            slot.dispersion += self.dispersion_increment;
+        }
+        self.register[self.next] = Some(Sample {
+            offset,
+            delay,
+            dispersion,
+            epoch,
+        });
+        self.next = (self.next + 1) % FILTER_SIZE;
+    }
+
+    fn best_sample(&self) -> Option<Sample> {
+        self.register
+            .iter()
+            .flatten()
+            .copied()
+            .min_by_key(|s| s.delay.as_fraction())
+    }
+
+    /// the offset of the currently selected (lowest-delay) sample
+    pub fn best_offset(&self) -> Option<NtpDuration> {
+        self.best_sample().map(|s| s.offset)
+    }
+
+    /// the dispersion of the currently selected (lowest-delay) sample
+    pub fn root_dispersion(&self) -> Option<i64> {
+        self.best_sample().map(|s| s.dispersion)
+    }
+
+    /// the poll epoch of the currently selected (lowest-delay) sample
+    pub fn best_epoch(&self) -> Option<u32> {
+        self.best_sample().map(|s| s.epoch)
+    }
+
+    /// RMS jitter of every stored offset around the selected best offset
+    pub fn jitter(&self) -> Option<i64> {
+        let best = self.best_sample()?;
+
+        let mut sum_sq: i128 = 0;
+        let mut count: i128 = 0;
+        for sample in self.register.iter().flatten() {
+            let diff = i128::from(sample.offset.as_fraction() - best.offset.as_fraction());
+            sum_sq += diff * diff;
+            count += 1;
+        }
+        if count == 0 {
+            return None;
+        }
+
+        Some(isqrt((sum_sq / count) as u64) as i64)
+    }
+}
+
+/// integer square root (floor), via Newton's method, so the filter stays
+/// usable without floating point support
+fn isqrt(n: u64) -> u64 {
+    if n == 0 {
+        return 0;
+    }
+    let mut x = n;
+    let mut y = x.div_ceil(2);
+    while y < x {
+        x = y;
+        y = (x + n / x) / 2;
+    }
+    x
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_sample_selects_lowest_delay() {
+        let mut filter = ClockFilter::new(1);
+
+        filter.add_sample(NtpDuration(0), NtpDuration(100), 1, 1);
+        filter.add_sample(NtpDuration(40), NtpDuration(50), 1, 2);
+        // lowest delay of the three, so this sample becomes the selected one
+        filter.add_sample(NtpDuration(-20), NtpDuration(20), 2, 3);
+
+        assert_eq!(filter.best_offset(), Some(NtpDuration(-20)));
+        assert_eq!(filter.best_epoch(), Some(3));
+        assert_eq!(filter.root_dispersion(), Some(2));
+        // RMS of [0, 40, -20] around the best offset (-20): sqrt((20^2 + 60^2 + 0^2) / 3)
+        assert_eq!(filter.jitter(), Some(36));
+    }
+}
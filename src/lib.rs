@@ -1,5 +1,74 @@
 #![no_std]
 
+pub mod clock_filter;
+
+/// an NTP timestamp: a 64-bit fixed-point value where the high 32 bits are
+/// whole seconds since the 1900 epoch and the low 32 bits are the fraction
+/// of a second, in units of 1/2^32 s. Kept as integer math so it stays
+/// usable on targets without floating point.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct NtpTimestamp(u64);
+
+impl NtpTimestamp {
+    /// build a timestamp from the raw 64-bit NTP fixed-point value
+    pub fn from_u64(value: u64) -> Self {
+        Self(value)
+    }
+
+    /// build a timestamp from separate whole-seconds and fraction halves,
+    /// as stored in the wire format for `tx_time`
+    pub fn from_parts(seconds: u32, fraction: u32) -> Self {
+        Self((u64::from(seconds) << 32) | u64::from(fraction))
+    }
+
+    /// build a timestamp from a unix `(seconds, nanoseconds)` pair, shifting
+    /// into the 1900 epoch and converting the fraction to NTP units
+    pub fn from_unix_nanos(secs: u32, nanos: u32) -> Self {
+        let fraction = (u64::from(nanos) * 4_294_967_296) / 1_000_000_000;
+        Self::from_parts(secs.wrapping_add(UNIX_OFFSET), fraction as u32)
+    }
+
+    /// split back into a unix `(seconds, nanoseconds)` pair
+    pub fn to_unix_nanos(self) -> (u32, u32) {
+        let nanos = (u64::from(self.fraction()) * 1_000_000_000) / 4_294_967_296;
+        (self.seconds().wrapping_sub(UNIX_OFFSET), nanos as u32)
+    }
+
+    /// whole seconds since the 1900 epoch
+    pub fn seconds(self) -> u32 {
+        (self.0 >> 32) as u32
+    }
+
+    /// fractional part of a second, in units of 1/2^32 s
+    pub fn fraction(self) -> u32 {
+        self.0 as u32
+    }
+
+    /// the raw 64-bit NTP fixed-point value
+    pub fn as_u64(self) -> u64 {
+        self.0
+    }
+}
+
+/// a signed NTP duration, in units of 1/2^32 s, as produced by differencing
+/// two [`NtpTimestamp`]s (e.g. clock offset or round-trip delay)
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct NtpDuration(i64);
+
+impl NtpDuration {
+    /// the raw signed value, in NTP fraction units (1/2^32 s)
+    pub fn as_fraction(self) -> i64 {
+        self.0
+    }
+
+    /// the duration in nanoseconds
+    pub fn as_nanos(self) -> i64 {
+        // widen to i128: `self.0 * 1_000_000_000` overflows i64 past ~2.147s,
+        // which round-trip delay and pre-sync offset routinely exceed
+        ((i128::from(self.0) * 1_000_000_000) / 4_294_967_296) as i64
+    }
+}
+
 #[derive(PartialEq, Debug)]
 pub struct PacketHeaders {
     /// indicate how leap second will be displayed
@@ -33,10 +102,6 @@ pub struct PacketHeaders {
     /// Time at the client when the reply arrived from the server, in NTP timestamp format.
     /// NOT included in packet header, client to set upon packet arrival
     dst_time: u64,
-    /// part of msg digst?
-    key_id: u32,
-    /// md5 hash of message?
-    msg_dgst: u128,
 }
 
 impl PacketHeaders {
@@ -44,69 +109,405 @@ impl PacketHeaders {
     pub fn get_unix_timestamp(self) -> u32 {
         self.tx_time_seconds - UNIX_OFFSET
     }
+
+    /// time when the system clock was last set or corrected
+    pub fn ref_time(&self) -> NtpTimestamp {
+        NtpTimestamp::from_u64(self.ref_time)
+    }
+
+    /// time at the client when the request departed for the server (T1)
+    pub fn origin_time(&self) -> NtpTimestamp {
+        NtpTimestamp::from_u64(self.origin_time)
+    }
+
+    /// time at the server when the request arrived from the client (T2)
+    pub fn rx_time(&self) -> NtpTimestamp {
+        NtpTimestamp::from_u64(self.rx_time)
+    }
+
+    /// time at the server when the response left for the client (T3)
+    pub fn tx_time(&self) -> NtpTimestamp {
+        NtpTimestamp::from_parts(self.tx_time_seconds, self.tx_time_fraction)
+    }
+
+    /// time at the client when the reply arrived from the server (T4)
+    pub fn dst_time(&self) -> NtpTimestamp {
+        NtpTimestamp::from_u64(self.dst_time)
+    }
+
+    /// record the time at the client when this reply arrived (T4), so
+    /// `offset()` and `round_trip_delay()` can be computed
+    pub fn set_dst_time(&mut self, dst_time: NtpTimestamp) {
+        self.dst_time = dst_time.as_u64();
+    }
+
+    /// round-trip delay per RFC 5905: `(T4 - T1) - (T3 - T2)`.
+    /// returns `None` if `origin_time` (T1) is unset or `dst_time` (T4)
+    /// hasn't been stamped yet via `set_dst_time`
+    pub fn round_trip_delay(&self) -> Option<NtpDuration> {
+        let (t1, t2, t3, t4) = self.wire_times()?;
+        Some(NtpDuration((t4 - t1) - (t3 - t2)))
+    }
+
+    /// clock offset per RFC 5905: `((T2 - T1) + (T3 - T4)) / 2`.
+    /// returns `None` if `origin_time` (T1) is unset or `dst_time` (T4)
+    /// hasn't been stamped yet via `set_dst_time`
+    pub fn offset(&self) -> Option<NtpDuration> {
+        let (t1, t2, t3, t4) = self.wire_times()?;
+        Some(NtpDuration(((t2 - t1) + (t3 - t4)) / 2))
+    }
+
+    /// the four NTP timestamps as signed fixed-point seconds, or `None` if
+    /// `origin_time` (T1) hasn't been filled in by a server response yet, or
+    /// `dst_time` (T4) hasn't been stamped yet via `set_dst_time`
+    fn wire_times(&self) -> Option<(i64, i64, i64, i64)> {
+        if self.origin_time == 0 || self.dst_time == 0 {
+            return None;
+        }
+        Some((
+            self.origin_time as i64,
+            self.rx_time as i64,
+            self.tx_time().as_u64() as i64,
+            self.dst_time as i64,
+        ))
+    }
+
+    /// serialize these headers back to the 48-byte wire format, packing
+    /// `li`/`vn`/`mode` into the first byte and writing every other field
+    /// in big-endian NTP order
+    pub fn to_bytes(&self) -> [u8; 48] {
+        let mut buff = [0_u8; 48];
+
+        let li: u8 = (&self.li).into();
+        let mode: u8 = (&self.mode).into();
+        buff[0] = (li << 6) | (self.vn << 3) | mode;
+        buff[1] = (&self.stratum).into();
+        buff[2] = self.poll as u8;
+        buff[3] = self.precision as u8;
+
+        buff[4..8].copy_from_slice(&self.root_delay.to_be_bytes());
+        buff[8..12].copy_from_slice(&self.root_dispersion.to_be_bytes());
+        buff[12..16].copy_from_slice(&self.ref_id.to_be_bytes());
+
+        buff[16..24].copy_from_slice(&self.ref_time.to_be_bytes());
+        buff[24..32].copy_from_slice(&self.origin_time.to_be_bytes());
+        buff[32..40].copy_from_slice(&self.rx_time.to_be_bytes());
+
+        buff[40..44].copy_from_slice(&self.tx_time_seconds.to_be_bytes());
+        buff[44..48].copy_from_slice(&self.tx_time_fraction.to_be_bytes());
+
+        buff
+    }
+}
+
+/// builds a [`PacketHeaders`] for an originated (client or symmetric) packet
+/// without requiring callers to hand-write the `li`/`vn`/`mode` bit packing
+pub struct PacketBuilder {
+    vn: u8,
+    mode: Mode,
+    poll: i8,
+    precision: i8,
+    origin_time: u64,
+}
+
+impl PacketBuilder {
+    pub fn new() -> Self {
+        Self {
+            vn: NTP_VERSION,
+            mode: Mode::Client,
+            poll: 0,
+            precision: 0,
+            origin_time: 0,
+        }
+    }
+
+    pub fn vn(mut self, vn: u8) -> Self {
+        self.vn = vn;
+        self
+    }
+
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn poll(mut self, poll: i8) -> Self {
+        self.poll = poll;
+        self
+    }
+
+    pub fn precision(mut self, precision: i8) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    /// the client's `origin_time` (T1), in NTP timestamp format
+    pub fn origin_time(mut self, origin_time: u64) -> Self {
+        self.origin_time = origin_time;
+        self
+    }
+
+    pub fn build(self) -> PacketHeaders {
+        PacketHeaders {
+            li: LI::NoLeap,
+            vn: self.vn,
+            mode: self.mode,
+            stratum: Stratum::UnspecifiedInvalid,
+            poll: self.poll,
+            precision: self.precision,
+            root_delay: 0,
+            root_dispersion: 0,
+            ref_id: 0,
+            ref_time: 0,
+            origin_time: self.origin_time,
+            rx_time: 0,
+            tx_time_seconds: 0,
+            tx_time_fraction: 0,
+            dst_time: 0,
+        }
+    }
 }
 
+impl Default for PacketBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// a single RFC 7822 extension field: a `data_type`/length-tagged value
+/// slice borrowed straight out of the original packet buffer
 #[derive(PartialEq, Debug)]
 pub struct ExtensionField<'a> {
-    data_type: u16,
-    data_length: u16,
-    data: &'a u8,
+    pub data_type: u16,
+    pub data: &'a [u8],
+}
+
+/// the trailing extension-field bytes of a packet, parsed lazily field by
+/// field rather than collected into an allocation
+#[derive(PartialEq, Debug)]
+pub struct ExtensionFields<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> ExtensionFields<'a> {
+    pub fn iter(&self) -> ExtensionFieldIter<'a> {
+        ExtensionFieldIter {
+            remaining: self.bytes,
+        }
+    }
+}
+
+impl<'a> IntoIterator for &ExtensionFields<'a> {
+    type Item = ExtensionField<'a>;
+    type IntoIter = ExtensionFieldIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+pub struct ExtensionFieldIter<'a> {
+    remaining: &'a [u8],
+}
+
+impl<'a> Iterator for ExtensionFieldIter<'a> {
+    type Item = ExtensionField<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining.len() < 4 {
+            return None;
+        }
+
+        let data_type = u16::from_be_bytes([self.remaining[0], self.remaining[1]]);
+        let length = u16::from_be_bytes([self.remaining[2], self.remaining[3]]) as usize;
+        if length < 4 || length > self.remaining.len() {
+            return None;
+        }
+
+        let data = &self.remaining[4..length];
+        // fields are padded out to a 4-byte boundary
+        let padded_length = ((length + 3) & !3).min(self.remaining.len());
+        self.remaining = &self.remaining[padded_length..];
+
+        Some(ExtensionField { data_type, data })
+    }
+}
+
+/// the key ID + digest trailer that authenticates a symmetric-key packet,
+/// covering every byte of the header and any extension fields that precede it
+#[derive(PartialEq, Debug)]
+struct MacTrailer<'a> {
+    key_id: u32,
+    digest: &'a [u8],
+    authenticated: &'a [u8],
+}
+
+/// common digest sizes (SHA-256, SHA-1, MD5) used to recognize a trailing
+/// MAC, tried longest first so a digest isn't mistaken for extension data
+const MAC_DIGEST_LENS: [usize; 3] = [32, 20, 16];
+
+/// computes a keyed message digest, so this crate can authenticate
+/// symmetric-key associations without depending on a specific hash
+/// implementation (MD5, SHA-1, SHA-256, or an AEAD for NTS cookies)
+pub trait MessageDigest {
+    /// writes the digest of `message` keyed by `key` into `out`, returning
+    /// how many bytes were written
+    fn digest(&self, key: &[u8], message: &[u8], out: &mut [u8; 32]) -> usize;
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut diff = 0_u8;
+    for (x, y) in a.iter().zip(b.iter()) {
+        diff |= x ^ y;
+    }
+    diff == 0
+}
+
+/// walks `bytes` as a run of RFC 7822 extension fields, returning the
+/// length of the last field parsed, or `None` if `bytes` isn't a
+/// well-formed run of fields
+fn last_extension_field_length(bytes: &[u8]) -> Option<usize> {
+    if bytes.is_empty() {
+        return None;
+    }
+    let mut rest = bytes;
+    let mut last_length = 0;
+    while !rest.is_empty() {
+        if rest.len() < 4 {
+            return None;
+        }
+        let length = u16::from_be_bytes([rest[2], rest[3]]) as usize;
+        if length < 4 || length > rest.len() {
+            return None;
+        }
+        last_length = length;
+        rest = &rest[((length + 3) & !3).min(rest.len())..];
+    }
+    Some(last_length)
+}
+
+/// does `bytes` look like a well-formed run of extension fields with no
+/// trailing garbage?
+fn looks_like_extension_fields(bytes: &[u8]) -> bool {
+    last_extension_field_length(bytes).is_some()
+}
+
+/// does `bytes` look like a well-formed run of extension fields that could
+/// plausibly precede a MAC trailer? RFC 7822 requires the *last* field
+/// before a MAC be at least 28 bytes, so a legacy MD5 trailer isn't
+/// mistaken for extension data; this rule only matters when there's a
+/// prospective MAC to disambiguate against.
+fn looks_like_extension_fields_before_mac(bytes: &[u8]) -> bool {
+    bytes.len() >= 28 && last_extension_field_length(bytes).is_some_and(|len| len >= 28)
+}
+
+/// an optional extension-field slice, alongside an optional `(key_id, digest)` MAC trailer
+type TrailerSplit<'a> = (Option<&'a [u8]>, Option<(u32, &'a [u8])>);
+
+/// splits the bytes following the 48-byte header into an optional
+/// extension-field slice and an optional MAC trailer
+fn split_trailer(remaining: &[u8]) -> TrailerSplit<'_> {
+    for digest_len in MAC_DIGEST_LENS {
+        let trailer_len = 4 + digest_len;
+        if remaining.len() < trailer_len {
+            continue;
+        }
+        let (ext_bytes, trailer_bytes) = remaining.split_at(remaining.len() - trailer_len);
+        if ext_bytes.is_empty() || looks_like_extension_fields_before_mac(ext_bytes) {
+            let key_id = read_u32(&trailer_bytes[..4]);
+            let digest = &trailer_bytes[4..];
+            let ext_bytes = if ext_bytes.is_empty() {
+                None
+            } else {
+                Some(ext_bytes)
+            };
+            return (ext_bytes, Some((key_id, digest)));
+        }
+    }
+
+    if remaining.is_empty() {
+        (None, None)
+    } else if looks_like_extension_fields(remaining) {
+        (Some(remaining), None)
+    } else {
+        (None, None)
+    }
 }
 
 #[derive(PartialEq, Debug)]
 pub struct NtpServerResponse<'a> {
     pub headers: PacketHeaders,
-    pub extension_fields: Option<[ExtensionField<'a>; 2]>,
+    pub extension_fields: Option<ExtensionFields<'a>>,
+    /// set when `stratum == 0` in a `Mode::Server` packet, in which case
+    /// `ref_id` carries a 4-byte ASCII kiss code instead of a reference ID
+    pub kiss_code: Option<KissCodes>,
+    mac: Option<MacTrailer<'a>>,
 }
 
-impl From<&[u8]> for NtpServerResponse<'_> {
-    fn from(value: &[u8]) -> Self {
-        let mut iter = value.iter();
-        let li_vn_mode = iter.next().unwrap();
-
-        // Extract the first two bits into the LI
-        let li = (li_vn_mode >> 6) & 0b11;
-        let li = li as u8;
-        let li: LI = li.into();
+impl NtpServerResponse<'_> {
+    /// the key ID of the symmetric-key MAC trailer, if one is present
+    pub fn key_id(&self) -> Option<u32> {
+        self.mac.as_ref().map(|mac| mac.key_id)
+    }
 
-        // Extract the next three bits for NTP version
-        let version = (li_vn_mode >> 3) & 0b111;
-        let version = version as u8;
+    /// recomputes the keyed digest over the header (and any extension
+    /// fields) and compares it, in constant time, against the trailing MAC
+    pub fn verify_mac<D: MessageDigest>(&self, key: &[u8], digest: &D) -> bool {
+        let Some(mac) = &self.mac else {
+            return false;
+        };
+        let mut computed = [0_u8; 32];
+        let len = digest.digest(key, mac.authenticated, &mut computed);
+        len == mac.digest.len() && constant_time_eq(&computed[..len], mac.digest)
+    }
+}
 
-        // Extract the next three bits for the Mode
-        let mode = (li_vn_mode) & 0b111;
-        let mode = mode as u8;
-        let mode: Mode = mode.into();
+/// error parsing a wire-format NTP packet
+#[derive(PartialEq, Debug)]
+pub enum NtpParseError {
+    /// the buffer was shorter than a 48-byte NTP header
+    TooShort { expected: usize, got: usize },
+    /// the 3-bit mode field held a value outside `0..=7`
+    InvalidMode,
+    /// the 2-bit leap indicator field held a value outside `0..=3`
+    InvalidLeapIndicator,
+}
 
-        let stratum = *iter.next().unwrap();
-        let stratum: Stratum = stratum.into();
-        // println!("{:?}, {}, {:?}, {:?}", li, version, mode, stratum);
+impl<'a> TryFrom<&'a [u8]> for NtpServerResponse<'a> {
+    type Error = NtpParseError;
 
-        let poll = *iter.next().unwrap() as i8;
-        let precision = *iter.next().unwrap() as i8;
+    fn try_from(value: &'a [u8]) -> Result<Self, Self::Error> {
+        if value.len() < 48 {
+            return Err(NtpParseError::TooShort {
+                expected: 48,
+                got: value.len(),
+            });
+        }
 
-        let root_delay = combine_u8s(&mut iter);
-        let root_dispersion = combine_u8s(&mut iter);
-        let ref_id = combine_u8s(&mut iter);
+        let li_vn_mode = value[0];
+        let li: LI = ((li_vn_mode >> 6) & 0b11).try_into()?;
+        let version = (li_vn_mode >> 3) & 0b111;
+        let mode: Mode = (li_vn_mode & 0b111).try_into()?;
 
-        // get times
-        let ref_seconds_1 = combine_u8s(&mut iter);
-        let ref_seconds_2 = combine_u8s(&mut iter);
-        let ref_time = (u64::from(ref_seconds_1) << 32) | (u64::from(ref_seconds_2));
+        let stratum: Stratum = value[1].into();
+        let poll = value[2] as i8;
+        let precision = value[3] as i8;
 
-        let ref_seconds_1 = combine_u8s(&mut iter);
-        let ref_seconds_2 = combine_u8s(&mut iter);
-        let origin_time = (u64::from(ref_seconds_1) << 32) | (u64::from(ref_seconds_2));
+        let root_delay = read_u32(&value[4..8]);
+        let root_dispersion = read_u32(&value[8..12]);
+        let ref_id = read_u32(&value[12..16]);
 
-        let ref_seconds_1 = combine_u8s(&mut iter);
-        let ref_seconds_2 = combine_u8s(&mut iter);
-        let rx_time = (u64::from(ref_seconds_1) << 32) | (u64::from(ref_seconds_2));
-        // println!("rx time: {rx_time}");
+        let ref_time = read_u64(&value[16..24]);
+        let origin_time = read_u64(&value[24..32]);
+        let rx_time = read_u64(&value[32..40]);
 
-        let tx_time_seconds = combine_u8s(&mut iter);
-        let tx_time_fraction = combine_u8s(&mut iter);
+        let tx_time_seconds = read_u32(&value[40..44]);
+        let tx_time_fraction = read_u32(&value[44..48]);
 
-        let headers: PacketHeaders = PacketHeaders {
+        let headers = PacketHeaders {
             li,
             vn: version,
             mode,
@@ -122,22 +523,39 @@ impl From<&[u8]> for NtpServerResponse<'_> {
             tx_time_seconds,
             tx_time_fraction,
             dst_time: 0,
-            key_id: 0,
-            msg_dgst: 0,
         };
-        NtpServerResponse {
+
+        // stratum 0 repurposes `ref_id` as a 4-byte ASCII kiss code, but only
+        // the server ever sends it this way
+        let kiss_code = if headers.stratum == Stratum::UnspecifiedInvalid && headers.mode == Mode::Server {
+            Some(KissCodes::from(&headers.ref_id.to_be_bytes()))
+        } else {
+            None
+        };
+
+        let (ext_bytes, trailer) = split_trailer(&value[48..]);
+        let extension_fields = ext_bytes.map(|bytes| ExtensionFields { bytes });
+        let mac = trailer.map(|(key_id, digest)| MacTrailer {
+            key_id,
+            digest,
+            authenticated: &value[..value.len() - 4 - digest.len()],
+        });
+
+        Ok(NtpServerResponse {
             headers,
-            extension_fields: None,
-        }
+            extension_fields,
+            kiss_code,
+            mac,
+        })
     }
 }
 
-fn combine_u8s(iter: &mut core::slice::Iter<'_, u8>) -> u32 {
-    let u8_1: u8 = *iter.next().unwrap();
-    let u8_2: u8 = *iter.next().unwrap();
-    let u8_3: u8 = *iter.next().unwrap();
-    let u8_4: u8 = *iter.next().unwrap();
-    (u32::from(u8_1) << 24) | (u32::from(u8_2) << 16) | (u32::from(u8_3) << 8) | u32::from(u8_4)
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes(bytes.try_into().expect("slice is 4 bytes"))
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    u64::from_be_bytes(bytes.try_into().expect("slice is 8 bytes"))
 }
 
 #[derive(PartialEq, Debug)]
@@ -148,14 +566,27 @@ pub enum LI {
     UnknownUnsync = 3,
 }
 
-impl From<u8> for LI {
-    fn from(value: u8) -> Self {
+impl TryFrom<u8> for LI {
+    type Error = NtpParseError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::NoLeap),
+            1 => Ok(Self::LastMinute61),
+            2 => Ok(Self::LastMinute59),
+            3 => Ok(Self::UnknownUnsync),
+            _ => Err(NtpParseError::InvalidLeapIndicator),
+        }
+    }
+}
+
+impl From<&LI> for u8 {
+    fn from(value: &LI) -> Self {
         match value {
-            0 => Self::NoLeap,
-            1 => Self::LastMinute61,
-            2 => Self::LastMinute59,
-            3 => Self::UnknownUnsync,
-            _ => panic!("impossible to be here"),
+            LI::NoLeap => 0,
+            LI::LastMinute61 => 1,
+            LI::LastMinute59 => 2,
+            LI::UnknownUnsync => 3,
         }
     }
 }
@@ -172,18 +603,35 @@ pub enum Mode {
     ReservedPrivateUse = 7,
 }
 
-impl From<u8> for Mode {
-    fn from(value: u8) -> Self {
+impl TryFrom<u8> for Mode {
+    type Error = NtpParseError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
         match value {
-            0 => Self::Reserved,
-            1 => Self::SymActive,
-            2 => Self::SymPassive,
-            3 => Self::Client,
-            4 => Self::Server,
-            5 => Self::Broadcast,
-            6 => Self::NtpControl,
-            7 => Self::ReservedPrivateUse,
-            _ => panic!("impossible"),
+            0 => Ok(Self::Reserved),
+            1 => Ok(Self::SymActive),
+            2 => Ok(Self::SymPassive),
+            3 => Ok(Self::Client),
+            4 => Ok(Self::Server),
+            5 => Ok(Self::Broadcast),
+            6 => Ok(Self::NtpControl),
+            7 => Ok(Self::ReservedPrivateUse),
+            _ => Err(NtpParseError::InvalidMode),
+        }
+    }
+}
+
+impl From<&Mode> for u8 {
+    fn from(value: &Mode) -> Self {
+        match value {
+            Mode::Reserved => 0,
+            Mode::SymActive => 1,
+            Mode::SymPassive => 2,
+            Mode::Client => 3,
+            Mode::Server => 4,
+            Mode::Broadcast => 5,
+            Mode::NtpControl => 6,
+            Mode::ReservedPrivateUse => 7,
         }
     }
 }
@@ -211,6 +659,20 @@ impl From<u8> for Stratum {
     }
 }
 
+impl From<&Stratum> for u8 {
+    /// `SecondaryServer` and `Reserved` collapse several wire values into one
+    /// variant, so this picks a representative byte from each range (2 and 17)
+    fn from(value: &Stratum) -> Self {
+        match value {
+            Stratum::UnspecifiedInvalid => 0,
+            Stratum::PrimaryServer => 1,
+            Stratum::SecondaryServer => 2,
+            Stratum::Unsynchronized => 16,
+            Stratum::Reserved => 17,
+        }
+    }
+}
+
 const UNIX_OFFSET: u32 = 2_208_988_800;
 pub const NTP_PORT: u8 = 123;
 pub const NTP_VERSION: u8 = 1;
@@ -218,6 +680,7 @@ pub const KISS_CODE_DENY: [u8; 4] = *b"DENY";
 pub const KISS_CODE_RSTR: [u8; 4] = *b"RSTR";
 pub const KISS_CODE_RATE: [u8; 4] = *b"RATE";
 
+#[derive(PartialEq, Debug)]
 pub enum KissCodes {
     UnicastServer,
     AuthFailed,
@@ -320,7 +783,8 @@ mod tests {
             209, 125, 239, 153, 206,
         ];
 
-        let ntp_response: NtpServerResponse = NtpServerResponse::from(values.as_ref());
+        let ntp_response: NtpServerResponse =
+            NtpServerResponse::try_from(values.as_ref()).unwrap();
         let expected = NtpServerResponse {
             headers: PacketHeaders {
                 li: LI::NoLeap,
@@ -338,10 +802,10 @@ mod tests {
                 tx_time_seconds: 3901482449,
                 tx_time_fraction: 2112854478,
                 dst_time: 0,
-                key_id: 0,
-                msg_dgst: 0,
             },
             extension_fields: None,
+            kiss_code: None,
+            mac: None,
         };
 
         assert_eq!(ntp_response, expected);
@@ -355,9 +819,165 @@ mod tests {
             98, 0, 0, 0, 0, 0, 0, 0, 0, 232, 140, 230, 180, 185, 134, 172, 167, 232, 140, 230, 180,
             185, 136, 186, 218,
         ];
-        let ntp_response: NtpServerResponse = NtpServerResponse::from(values.as_ref());
+        let ntp_response: NtpServerResponse =
+            NtpServerResponse::try_from(values.as_ref()).unwrap();
 
         assert_eq!(ntp_response.headers.tx_time_seconds, 3901548212_u32);
         assert_eq!(ntp_response.headers.get_unix_timestamp(), 1692559412);
     }
+
+    #[test]
+    fn test_builder_to_bytes_round_trip() {
+        let headers = PacketBuilder::new()
+            .vn(4)
+            .mode(Mode::Client)
+            .poll(6)
+            .precision(-20)
+            .origin_time(0x1122_3344_5566_7788)
+            .build();
+
+        let bytes = headers.to_bytes();
+        let parsed = NtpServerResponse::try_from(bytes.as_ref()).unwrap();
+
+        assert_eq!(parsed.headers.vn, 4);
+        assert_eq!(parsed.headers.mode, Mode::Client);
+        assert_eq!(parsed.headers.poll, 6);
+        assert_eq!(parsed.headers.precision, -20);
+        assert_eq!(
+            parsed.headers.origin_time(),
+            NtpTimestamp::from_u64(0x1122_3344_5566_7788)
+        );
+        assert_eq!(parsed.kiss_code, None);
+    }
+
+    #[test]
+    fn test_unix_nanos_round_trip() {
+        let ts = NtpTimestamp::from_unix_nanos(1_700_000_000, 123_456_789);
+        let (secs, nanos) = ts.to_unix_nanos();
+
+        assert_eq!(secs, 1_700_000_000);
+        // the fraction/nanosecond conversion is inherently lossy by a
+        // nanosecond or two, since neither unit divides the other evenly
+        assert!(
+            (nanos as i64 - 123_456_789_i64).abs() <= 1,
+            "got {nanos}, expected ~123456789"
+        );
+    }
+
+    #[test]
+    fn test_offset_and_round_trip_delay() {
+        // T1 = 10.0s, T2 = 10.5s, T3 = 11.0s, T4 = 11.5s, in NTP fixed-point
+        // seconds (1 << 32 units per second)
+        let t1 = 10_u64 << 32;
+        let t2 = (10_u64 << 32) + (1_u64 << 31);
+        let t3 = 11_u64 << 32;
+        let t4 = (11_u64 << 32) + (1_u64 << 31);
+
+        let mut headers = PacketBuilder::new().build();
+        headers.origin_time = t1;
+        headers.rx_time = t2;
+        headers.tx_time_seconds = (t3 >> 32) as u32;
+        headers.tx_time_fraction = t3 as u32;
+        headers.set_dst_time(NtpTimestamp::from_u64(t4));
+
+        // delay = (T4 - T1) - (T3 - T2) = 1.5s - 0.5s = 1.0s
+        assert_eq!(headers.round_trip_delay(), Some(NtpDuration(1_i64 << 32)));
+        // offset = ((T2 - T1) + (T3 - T4)) / 2 = (0.5s - 0.5s) / 2 = 0
+        assert_eq!(headers.offset(), Some(NtpDuration(0)));
+    }
+
+    #[test]
+    fn test_offset_none_before_origin_time_set() {
+        let headers = PacketBuilder::new().build();
+
+        assert_eq!(headers.round_trip_delay(), None);
+        assert_eq!(headers.offset(), None);
+    }
+
+    #[test]
+    fn test_stratum_0_server_packet_yields_kiss_code() {
+        let mut values = [0_u8; 48];
+        // li = 0, vn = 4, mode = Server (4)
+        values[0] = (4 << 3) | 4;
+        // stratum 0: ref_id is repurposed as a 4-byte ASCII kiss code
+        values[12..16].copy_from_slice(b"RATE");
+
+        let ntp_response = NtpServerResponse::try_from(values.as_ref()).unwrap();
+
+        assert_eq!(ntp_response.kiss_code, Some(KissCodes::RateExceeded));
+    }
+
+    #[test]
+    fn test_parse_error_too_short() {
+        let values = [0_u8; 10];
+
+        assert_eq!(
+            NtpServerResponse::try_from(values.as_ref()),
+            Err(NtpParseError::TooShort {
+                expected: 48,
+                got: 10,
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_error_invalid_mode() {
+        assert_eq!(Mode::try_from(8), Err(NtpParseError::InvalidMode));
+    }
+
+    #[test]
+    fn test_parse_error_invalid_leap_indicator() {
+        assert_eq!(LI::try_from(4), Err(NtpParseError::InvalidLeapIndicator));
+    }
+
+    /// a toy keyed digest, just for exercising extension-field parsing and
+    /// `verify_mac` without depending on a real hash implementation
+    struct XorDigest;
+
+    impl MessageDigest for XorDigest {
+        fn digest(&self, key: &[u8], message: &[u8], out: &mut [u8; 32]) -> usize {
+            let mut acc = [0_u8; 16];
+            for (i, b) in key.iter().chain(message.iter()).enumerate() {
+                acc[i % 16] ^= b;
+            }
+            out[..16].copy_from_slice(&acc);
+            16
+        }
+    }
+
+    #[test]
+    fn test_extension_fields_and_verify_mac() {
+        let key = b"test-symmetric-key";
+        let digest = XorDigest;
+
+        // a 48-byte header (any values are fine here) followed by one
+        // 28-byte extension field and a 20-byte (4 + 16) MAC trailer
+        let mut packet = [0_u8; 96];
+        packet[0] = (4 << 3) | 3; // vn = 4, mode = Client
+
+        packet[48..50].copy_from_slice(&0x0104_u16.to_be_bytes());
+        packet[50..52].copy_from_slice(&28_u16.to_be_bytes());
+        for (i, b) in packet[52..76].iter_mut().enumerate() {
+            *b = i as u8;
+        }
+
+        let key_id = 0xAABB_CCDD_u32;
+        packet[76..80].copy_from_slice(&key_id.to_be_bytes());
+
+        let mut mac = [0_u8; 32];
+        let len = digest.digest(key.as_ref(), &packet[..76], &mut mac);
+        packet[80..80 + len].copy_from_slice(&mac[..len]);
+
+        let response = NtpServerResponse::try_from(packet.as_ref()).unwrap();
+
+        let mut fields = response.extension_fields.as_ref().unwrap().iter();
+        let field = fields.next().unwrap();
+        assert_eq!(field.data_type, 0x0104);
+        assert_eq!(field.data, &packet[52..76]);
+        assert!(fields.next().is_none());
+
+        assert_eq!(response.key_id(), Some(key_id));
+        assert!(response.verify_mac(key.as_ref(), &digest));
+        assert!(!response.verify_mac(b"wrong-key", &digest));
+    }
 }